@@ -2,6 +2,11 @@
 //! Solutions to the [Synacor Challenge](https://challenge.synacor.com/).
 
 use std::io::Read;
+use std::error::Error;
+use std::fmt;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 
 type Arg = u16;
 
@@ -32,13 +37,241 @@ enum Op {
     Invalid(Arg),
 }
 
+/// A single decoded instruction, as produced by `Machine::disassemble`.
+///
+/// `opcode` is `None` when the word at `addr` falls within a caller-supplied
+/// data range and was skipped rather than decoded.
+struct Instr {
+    addr: u16,
+    opcode: Option<u16>,
+    args: Vec<u16>,
+}
+
+/// A parsed, not-yet-resolved line of assembly source, as produced while
+/// parsing in `Machine::assemble`.
+enum Stmt {
+    /// A `data` directive; each entry is resolved the same as an
+    /// instruction argument.
+    Data(Vec<String>),
+    /// An instruction: its opcode and its raw, unresolved argument tokens.
+    Instr(u16, Vec<String>),
+}
+
+/// Errors that can occur while assembling a textual program with
+/// `Machine::assemble`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    /// The mnemonic on a line wasn't recognized.
+    UnknownMnemonic {
+        /// The unrecognized mnemonic text.
+        mnemonic: String,
+        /// The 1-based source line it appeared on.
+        line: usize,
+    },
+    /// An instruction was given the wrong number of arguments.
+    WrongArgCount {
+        /// The mnemonic whose arguments didn't match.
+        mnemonic: String,
+        /// The number of arguments the mnemonic requires.
+        expected: usize,
+        /// The number of arguments actually given.
+        found: usize,
+        /// The 1-based source line it appeared on.
+        line: usize,
+    },
+    /// An argument wasn't a valid register, literal, or label.
+    InvalidArgument {
+        /// The offending argument text.
+        text: String,
+        /// The 1-based source line it appeared on.
+        line: usize,
+    },
+    /// A label was referenced but never defined.
+    UndefinedLabel {
+        /// The undefined label's name.
+        label: String,
+        /// The 1-based source line it appeared on.
+        line: usize,
+    },
+    /// The same label was defined more than once.
+    DuplicateLabel {
+        /// The label defined twice.
+        label: String,
+        /// The 1-based source line of the second definition.
+        line: usize,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsmError::UnknownMnemonic { ref mnemonic, line } => {
+                write!(f, "line {}: unknown mnemonic '{}'", line, mnemonic)
+            }
+            AsmError::WrongArgCount { ref mnemonic, expected, found, line } => {
+                write!(f,
+                       "line {}: '{}' expects {} argument(s), found {}",
+                       line,
+                       mnemonic,
+                       expected,
+                       found)
+            }
+            AsmError::InvalidArgument { ref text, line } => {
+                write!(f, "line {}: invalid argument '{}'", line, text)
+            }
+            AsmError::UndefinedLabel { ref label, line } => {
+                write!(f, "line {}: undefined label '{}'", line, label)
+            }
+            AsmError::DuplicateLabel { ref label, line } => {
+                write!(f, "line {}: label '{}' defined more than once", line, label)
+            }
+        }
+    }
+}
+
+impl Error for AsmError {
+    fn description(&self) -> &str {
+        match *self {
+            AsmError::UnknownMnemonic { .. } => "unknown mnemonic",
+            AsmError::WrongArgCount { .. } => "wrong argument count",
+            AsmError::InvalidArgument { .. } => "invalid argument",
+            AsmError::UndefinedLabel { .. } => "undefined label",
+            AsmError::DuplicateLabel { .. } => "duplicate label",
+        }
+    }
+}
+
+/// Errors that can occur while decoding or executing a program on a
+/// `Machine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// A `POP` was executed with an empty stack. (A `RET` on an empty
+    /// stack halts the machine instead.)
+    StackUnderflow,
+    /// An instruction argument was neither a 15-bit literal nor one of the
+    /// eight register addresses.
+    InvalidArgument(u16),
+    /// An `IN` instruction was executed but its input source (stdin or a
+    /// scripted input queue) had no more bytes to give.
+    InputEof,
+    /// The decoded opcode isn't one the VM understands.
+    InvalidOpcode {
+        /// The unrecognized opcode value.
+        op: u16,
+        /// The instruction pointer at which it was found.
+        ip: u16,
+    },
+    /// A memory access fell outside of the addressable 15-bit range.
+    MemoryOutOfBounds(u16),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::StackUnderflow => write!(f, "stack underflow"),
+            VmError::InvalidArgument(arg) => write!(f, "invalid argument: {}", arg),
+            VmError::InputEof => write!(f, "unexpected EOF while reading input"),
+            VmError::InvalidOpcode { op, ip } => {
+                write!(f, "invalid opcode {} at address {}", op, ip)
+            }
+            VmError::MemoryOutOfBounds(addr) => {
+                write!(f, "memory access out of bounds: {}", addr)
+            }
+        }
+    }
+}
+
+impl Error for VmError {
+    fn description(&self) -> &str {
+        match *self {
+            VmError::StackUnderflow => "stack underflow",
+            VmError::InvalidArgument(_) => "invalid argument",
+            VmError::InputEof => "unexpected EOF while reading input",
+            VmError::InvalidOpcode { .. } => "invalid opcode",
+            VmError::MemoryOutOfBounds(_) => "memory access out of bounds",
+        }
+    }
+}
+
+/// A native handler invoked in place of the bytecode at a `Call` target.
+///
+/// See `Machine::register_call_handler`.
+pub type CallHandler = Box<FnMut(&mut Machine) -> Result<(), VmError>>;
+
+/// A native handler that supplies the value for an `In` instruction.
+///
+/// See `Machine::set_input_handler`.
+pub type InputHandler = Box<FnMut(&mut Machine) -> Result<u16, VmError>>;
+
+/// A native handler invoked for an `Out` instruction's value.
+///
+/// See `Machine::set_output_handler`.
+pub type OutputHandler = Box<FnMut(&mut Machine, u16) -> Result<(), VmError>>;
+
+/// A register or memory cell that can be watched for changes with
+/// `Machine::add_watchpoint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    /// One of the eight registers, `0..=7`.
+    Register(u16),
+    /// A memory address.
+    Memory(u16),
+}
+
+/// The result of executing one instruction under `Machine::step`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction executed normally; execution can continue.
+    Continued,
+    /// The machine halted.
+    Halted,
+    /// Execution stopped without running the instruction at this address,
+    /// because a breakpoint is set there. `step` keeps returning this while
+    /// the IP stays on the breakpoint; `cont` steps over it.
+    Breakpoint(u16),
+    /// A watched register or memory cell changed from `old` to `new`.
+    Watchpoint(WatchTarget, u16, u16),
+}
+
+/// A point-in-time snapshot of a `Machine`'s program state, as produced by
+/// `Machine::snapshot` and consumed by `Machine::restore`.
+///
+/// Native call/input/output handlers are host-side configuration rather
+/// than program state, so they are not part of the snapshot.
+#[derive(Debug, Clone)]
+pub struct MachineState {
+    memory: Vec<u16>,
+    registers: Vec<u16>,
+    stack: Vec<u16>,
+    ip: u16,
+}
+
 /// Represents a virtual machine used in the Synacor Challenge.
-#[derive(Debug)]
 pub struct Machine {
     memory: Vec<u16>,
     registers: Vec<u16>,
     stack: Vec<u16>,
     ip: u16,
+    call_handlers: HashMap<u16, CallHandler>,
+    input_handler: Option<InputHandler>,
+    output_handler: Option<OutputHandler>,
+    breakpoints: HashSet<u16>,
+    watchpoints: Vec<(WatchTarget, u16)>,
+    call_trace: Vec<u16>,
+}
+
+impl fmt::Debug for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("memory", &self.memory)
+            .field("registers", &self.registers)
+            .field("stack", &self.stack)
+            .field("ip", &self.ip)
+            .field("breakpoints", &self.breakpoints)
+            .field("watchpoints", &self.watchpoints)
+            .field("call_trace", &self.call_trace)
+            .finish()
+    }
 }
 
 impl Machine {
@@ -49,9 +282,308 @@ impl Machine {
             registers: vec![0; 8],
             stack: vec![],
             ip: 0,
+            call_handlers: HashMap::new(),
+            input_handler: None,
+            output_handler: None,
+            breakpoints: HashSet::new(),
+            watchpoints: vec![],
+            call_trace: vec![],
         }
     }
 
+    /// Registers a native handler to run whenever a `Call` targets `addr`.
+    ///
+    /// Instead of jumping into the bytecode at `addr`, the VM invokes
+    /// `handler` and then behaves as if an immediate `Ret` had been
+    /// executed against it, leaving the IP where it was after the `Call`.
+    /// This lets host code intercept subroutines (for example to replace a
+    /// slow interpreted routine with a fast native equivalent) without
+    /// modifying the loaded program.
+    pub fn register_call_handler<F>(&mut self, addr: u16, handler: F)
+        where F: FnMut(&mut Machine) -> Result<(), VmError> + 'static
+    {
+        self.call_handlers.insert(addr, Box::new(handler));
+    }
+
+    /// Removes a previously registered call handler, if any.
+    pub fn remove_call_handler(&mut self, addr: u16) {
+        self.call_handlers.remove(&addr);
+    }
+
+    /// Overrides how `In` instructions obtain their value.
+    ///
+    /// By default `In` reads a byte from stdin; registering a handler lets
+    /// the VM be embedded without touching real stdin, for example to
+    /// drive it from a scripted input source.
+    pub fn set_input_handler<F>(&mut self, handler: F)
+        where F: FnMut(&mut Machine) -> Result<u16, VmError> + 'static
+    {
+        self.input_handler = Some(Box::new(handler));
+    }
+
+    /// Removes a previously registered input handler, reverting to stdin.
+    pub fn clear_input_handler(&mut self) {
+        self.input_handler = None;
+    }
+
+    /// Overrides how `Out` instructions consume their value.
+    ///
+    /// By default `Out` prints the value as a character to stdout;
+    /// registering a handler lets the VM be embedded without touching real
+    /// stdout.
+    pub fn set_output_handler<F>(&mut self, handler: F)
+        where F: FnMut(&mut Machine, u16) -> Result<(), VmError> + 'static
+    {
+        self.output_handler = Some(Box::new(handler));
+    }
+
+    /// Removes a previously registered output handler, reverting to stdout.
+    pub fn clear_output_handler(&mut self) {
+        self.output_handler = None;
+    }
+
+    /// Feeds `Op::In` from a fixed, pre-supplied queue of bytes instead of
+    /// stdin, returning `VmError::InputEof` once it is exhausted.
+    ///
+    /// This enables headless, scripted playthroughs and backtracking
+    /// search that don't depend on real stdin.
+    pub fn set_scripted_input(&mut self, mut input: VecDeque<u8>) {
+        self.set_input_handler(move |_| {
+            input.pop_front().map(|b| b as u16).ok_or(VmError::InputEof)
+        });
+    }
+
+    /// Captures the machine's current memory, registers, stack, and IP.
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            memory: self.memory.clone(),
+            registers: self.registers.clone(),
+            stack: self.stack.clone(),
+            ip: self.ip,
+        }
+    }
+
+    /// Restores memory, registers, stack, and IP from a prior snapshot.
+    pub fn restore(&mut self, state: &MachineState) {
+        self.memory = state.memory.clone();
+        self.registers = state.registers.clone();
+        self.stack = state.stack.clone();
+        self.ip = state.ip;
+    }
+
+    /// Returns the machine's register file.
+    pub fn registers(&self) -> &[u16] {
+        &self.registers
+    }
+
+    /// Returns the machine's current stack, bottom first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Returns the machine's current instruction pointer.
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
+
+    /// Returns the machine's full memory.
+    pub fn memory(&self) -> &[u16] {
+        &self.memory
+    }
+
+    /// Returns the current call-stack backtrace: return addresses pushed
+    /// by `Call` and not yet consumed by a matching `Ret`, oldest first.
+    pub fn backtrace(&self) -> &[u16] {
+        &self.call_trace
+    }
+
+    /// Sets a breakpoint on instruction address `addr`.
+    ///
+    /// `step` will report `StepOutcome::Breakpoint` the next time the IP
+    /// reaches `addr` instead of executing the instruction there.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Removes a previously set breakpoint, if any.
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Watches a register or memory cell for changes.
+    ///
+    /// `step` will report `StepOutcome::Watchpoint` the next time its
+    /// value differs from the value it held when this was called. Returns
+    /// `VmError::InvalidArgument` for a register outside `0..=7` or
+    /// `VmError::MemoryOutOfBounds` for an out-of-range memory address,
+    /// rather than panicking.
+    pub fn add_watchpoint(&mut self, target: WatchTarget) -> Result<(), VmError> {
+        let value = try!(self.watch_value(target));
+        self.watchpoints.push((target, value));
+        Ok(())
+    }
+
+    /// Clears all watchpoints.
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    fn watch_value(&self, target: WatchTarget) -> Result<u16, VmError> {
+        match target {
+            WatchTarget::Register(r) => {
+                self.registers.get(r as usize).cloned().ok_or(VmError::InvalidArgument(r))
+            }
+            WatchTarget::Memory(addr) => {
+                self.memory.get(addr as usize).cloned().ok_or(VmError::MemoryOutOfBounds(addr))
+            }
+        }
+    }
+
+    /// Executes one instruction and reports whether it halted the machine
+    /// or tripped a watchpoint, the shared tail end of both `step` and
+    /// `cont`'s breakpoint-skip so neither can bypass the watchpoint scan.
+    fn tick_and_scan_watchpoints(&mut self) -> Result<StepOutcome, VmError> {
+        if !try!(self.tick()) {
+            return Ok(StepOutcome::Halted);
+        }
+
+        for i in 0..self.watchpoints.len() {
+            let (target, last) = self.watchpoints[i];
+            let current = try!(self.watch_value(target));
+            if current != last {
+                self.watchpoints[i] = (target, current);
+                return Ok(StepOutcome::Watchpoint(target, last, current));
+            }
+        }
+
+        Ok(StepOutcome::Continued)
+    }
+
+    /// Executes a single instruction, honoring breakpoints and watchpoints.
+    ///
+    /// If a breakpoint is set on the address about to execute, the
+    /// instruction is *not* run and `StepOutcome::Breakpoint` is returned;
+    /// calling `step` again returns the same result, since `step` never
+    /// executes an instruction that has a breakpoint on it (`cont` steps
+    /// over it). Otherwise the
+    /// instruction runs and, if a watched register or memory cell
+    /// changed, `StepOutcome::Watchpoint` is returned instead of
+    /// `StepOutcome::Continued`.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        if self.breakpoints.contains(&self.ip) {
+            return Ok(StepOutcome::Breakpoint(self.ip));
+        }
+
+        self.tick_and_scan_watchpoints()
+    }
+
+    /// Executes instructions via `step` until a breakpoint, watchpoint, or
+    /// halt is hit.
+    ///
+    /// If the IP is already sitting on a breakpoint when `cont` is
+    /// called, that breakpoint is stepped over once so `cont` always
+    /// makes progress. That initial step still runs through the same
+    /// watchpoint scan as `step`, so a watchpoint tripped by the skipped
+    /// instruction is reported rather than silently dropped.
+    pub fn cont(&mut self) -> Result<StepOutcome, VmError> {
+        if self.breakpoints.contains(&self.ip) {
+            match try!(self.tick_and_scan_watchpoints()) {
+                StepOutcome::Continued => {}
+                other => return Ok(other),
+            }
+        }
+
+        loop {
+            match try!(self.step()) {
+                StepOutcome::Continued => continue,
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Applies the affine map `x -> m*x + c` (mod 32768) to `x0`, `n`
+    /// times, via binary exponentiation on the map itself so `n` up to
+    /// 32767 costs O(log n) instead of O(n) iterations.
+    fn iterate_affine(m: u32, c: u32, n: u16, x0: u16) -> u16 {
+        const MODULUS: u32 = 32768;
+        let (mut total_m, mut total_c) = (1u32, 0u32);
+        let (mut cur_m, mut cur_c) = (m % MODULUS, c % MODULUS);
+        let mut n = n;
+        while n > 0 {
+            if n & 1 == 1 {
+                let composed_c = (cur_m * total_c + cur_c) % MODULUS;
+                total_m = (cur_m * total_m) % MODULUS;
+                total_c = composed_c;
+            }
+            let squared_c = (cur_m * cur_c + cur_c) % MODULUS;
+            cur_m = (cur_m * cur_m) % MODULUS;
+            cur_c = squared_c;
+            n >>= 1;
+        }
+        ((total_m * x0 as u32 + total_c) % MODULUS) as u16
+    }
+
+    /// Computes the challenge's teleporter verification routine:
+    ///
+    /// `f(0, b) = (b + 1) mod 32768`
+    /// `f(a, 0) = f(a - 1, r8)`
+    /// `f(a, b) = f(a - 1, f(a, b - 1))`
+    ///
+    /// `a` is always small (`0..=4`). Levels 0-2 collapse to closed-form
+    /// affine maps of `b`, derived by unrolling the recursion: `f(1, b) =
+    /// b + r8 + 1`, and `f(2, b) = (r8 + 1) * b + (2 * r8 + 1)` because
+    /// repeatedly applying `f(1, _)` is itself an affine map. Levels 3
+    /// and 4 are then evaluated by iterating the level-2 map via
+    /// `iterate_affine` instead of materializing a 32768-entry table per
+    /// call, which keeps `calibrate_teleporter` fast.
+    pub fn teleporter_check(a: u16, b: u16, r8: u16) -> u16 {
+        const MODULUS: u32 = 32768;
+        let a2 = (r8 as u32 + 1) % MODULUS;
+        let b2 = (2 * r8 as u32 + 1) % MODULUS;
+        let level2 = |x: u32| ((a2 * x + b2) % MODULUS) as u16;
+
+        match a {
+            0 => ((b as u32 + 1) % MODULUS) as u16,
+            1 => ((b as u32 + r8 as u32 + 1) % MODULUS) as u16,
+            2 => level2(b as u32),
+            3 => Self::iterate_affine(a2, b2, b, level2(r8 as u32)),
+            _ => {
+                // a == 4: f(4, 0) = f(3, r8); f(4, b) = f(3, f(4, b - 1)).
+                let level3 = |n| Self::iterate_affine(a2, b2, n, level2(r8 as u32));
+                let mut value = level3(r8);
+                for _ in 0..b {
+                    value = level3(value);
+                }
+                value
+            }
+        }
+    }
+
+    /// Searches `r8` in `0..32768` for the value that makes
+    /// `teleporter_check(4, 1, r8)` equal `expected`, the constant the
+    /// teleporter routine checks register 0 against. Returns `None` if no
+    /// such value exists.
+    pub fn calibrate_teleporter(expected: u16) -> Option<u16> {
+        (0..32768u32).map(|r8| r8 as u16).find(|&r8| Self::teleporter_check(4, 1, r8) == expected)
+    }
+
+    /// Registers a native handler at `addr` that substitutes
+    /// `teleporter_check` for the challenge's interpreted verification
+    /// routine, reading `a` from register 0, `b` from register 1, and
+    /// `r8` from register 7 (the puzzle's "eighth register"), and writing
+    /// the result back to register 0, matching the routine's own calling
+    /// convention.
+    pub fn install_teleporter_handler(&mut self, addr: u16) {
+        self.register_call_handler(addr, |machine| {
+            let a = machine.registers()[0];
+            let b = machine.registers()[1];
+            let r8 = machine.registers()[7];
+            let result = Machine::teleporter_check(a, b, r8);
+            machine.set_register(32768, result);
+            Ok(())
+        });
+    }
+
     /// Loads a program from the filesystem into memory.
     ///
     /// If successful, this function will return the total number of
@@ -72,12 +604,15 @@ impl Machine {
         Ok(read)
     }
 
-    /// Reads from memory at the IP and increment it.
-    fn next(&mut self) -> u16 {
+    /// Reads from memory at the IP and increments it.
+    fn next(&mut self) -> Result<u16, VmError> {
         let ip = self.ip as usize;
-        let ret = self.memory[ip];
+        let ret = try!(self.memory
+                            .get(ip)
+                            .cloned()
+                            .ok_or(VmError::MemoryOutOfBounds(self.ip)));
         self.ip = (ip + 1) as u16;
-        ret
+        Ok(ret)
     }
 
     fn get_register(&self, reg: Arg) -> u16 {
@@ -89,43 +624,44 @@ impl Machine {
     }
 
     /// Decodes an instruction argument, fetching from a register if necessary.
-    fn value(&self, arg: Arg) -> u16 {
+    fn value(&self, arg: Arg) -> Result<u16, VmError> {
         if arg <= 32767 {
-            arg
+            Ok(arg)
         } else if arg <= 32775 {
-            self.get_register(arg)
+            Ok(self.get_register(arg))
         } else {
-            panic!("Invalid argument")
+            Err(VmError::InvalidArgument(arg))
         }
     }
 
     /// Decodes the next instruction, incrementing the IP as appropriate.
-    fn decode(&mut self) -> Op {
-        match self.next() {
+    fn decode(&mut self) -> Result<Op, VmError> {
+        let op = match try!(self.next()) {
             0 => Op::Halt,
-            1 => Op::Set(self.next(), self.next()),
-            2 => Op::Push(self.next()),
-            3 => Op::Pop(self.next()),
-            4 => Op::Eq(self.next(), self.next(), self.next()),
-            5 => Op::Gt(self.next(), self.next(), self.next()),
-            6 => Op::Jmp(self.next()),
-            7 => Op::Jt(self.next(), self.next()),
-            8 => Op::Jf(self.next(), self.next()),
-            9 => Op::Add(self.next(), self.next(), self.next()),
-            10 => Op::Mult(self.next(), self.next(), self.next()),
-            11 => Op::Mod(self.next(), self.next(), self.next()),
-            12 => Op::And(self.next(), self.next(), self.next()),
-            13 => Op::Or(self.next(), self.next(), self.next()),
-            14 => Op::Not(self.next(), self.next()),
-            15 => Op::Rmem(self.next(), self.next()),
-            16 => Op::Wmem(self.next(), self.next()),
-            17 => Op::Call(self.next()),
+            1 => Op::Set(try!(self.next()), try!(self.next())),
+            2 => Op::Push(try!(self.next())),
+            3 => Op::Pop(try!(self.next())),
+            4 => Op::Eq(try!(self.next()), try!(self.next()), try!(self.next())),
+            5 => Op::Gt(try!(self.next()), try!(self.next()), try!(self.next())),
+            6 => Op::Jmp(try!(self.next())),
+            7 => Op::Jt(try!(self.next()), try!(self.next())),
+            8 => Op::Jf(try!(self.next()), try!(self.next())),
+            9 => Op::Add(try!(self.next()), try!(self.next()), try!(self.next())),
+            10 => Op::Mult(try!(self.next()), try!(self.next()), try!(self.next())),
+            11 => Op::Mod(try!(self.next()), try!(self.next()), try!(self.next())),
+            12 => Op::And(try!(self.next()), try!(self.next()), try!(self.next())),
+            13 => Op::Or(try!(self.next()), try!(self.next()), try!(self.next())),
+            14 => Op::Not(try!(self.next()), try!(self.next())),
+            15 => Op::Rmem(try!(self.next()), try!(self.next())),
+            16 => Op::Wmem(try!(self.next()), try!(self.next())),
+            17 => Op::Call(try!(self.next())),
             18 => Op::Ret,
-            19 => Op::Out(self.next()),
-            20 => Op::In(self.next()),
+            19 => Op::Out(try!(self.next())),
+            20 => Op::In(try!(self.next())),
             21 => Op::Noop,
             inv => Op::Invalid(inv),
-        }
+        };
+        Ok(op)
     }
 
 
@@ -141,132 +677,694 @@ impl Machine {
         res as u16
     }
 
-    /// Decodes and runs the next instruction.
+    /// Renders the memory between `start` (inclusive) and `end` (exclusive)
+    /// as an annotated instruction listing, one `(address, line)` pair per
+    /// instruction.
     ///
-    /// Returns `false` if a `HALT` or invalid instruction was encountered,
-    /// true otherwise.
+    /// `data_ranges` is a list of `[start, end)` spans known to hold raw
+    /// data rather than code; they are rendered as `<data>` rather than
+    /// misdecoded as instructions. Runs of `OUT` instructions that print
+    /// printable characters are annotated with the string they produce, so
+    /// the listing reads naturally even though the binary mixes code and
+    /// data.
+    pub fn disassemble(&self, start: u16, end: u16, data_ranges: &[(u16, u16)]) -> Vec<(u16, String)> {
+        let instrs = self.decode_range(start, end, data_ranges);
+        Self::render(&instrs)
+    }
+
+    fn decode_range(&self, start: u16, end: u16, data_ranges: &[(u16, u16)]) -> Vec<Instr> {
+        let mut instrs = vec![];
+        let end = (end as usize).min(self.memory.len());
+        let mut pos = start as usize;
+        while pos < end {
+            let skip_to = data_ranges.iter()
+                                      .find(|&&(s, e)| pos >= s as usize && pos < e as usize)
+                                      .map(|&(_, e)| e as usize);
+            if let Some(skip_to) = skip_to {
+                instrs.push(Instr {
+                    addr: pos as u16,
+                    opcode: None,
+                    args: vec![],
+                });
+                pos = skip_to.min(end);
+                continue;
+            }
+
+            let opcode = self.memory[pos];
+            let len = Self::op_length(opcode);
+            let word_end = (pos + len).min(self.memory.len());
+            let args = self.memory[(pos + 1)..word_end].to_vec();
+            instrs.push(Instr {
+                addr: pos as u16,
+                opcode: Some(opcode),
+                args: args,
+            });
+            pos += len.max(1);
+        }
+        instrs
+    }
+
+    fn render(instrs: &[Instr]) -> Vec<(u16, String)> {
+        let mut out = vec![];
+        let mut i = 0;
+        while i < instrs.len() {
+            if instrs[i].opcode.is_none() {
+                out.push((instrs[i].addr, format!("{}: <data>", instrs[i].addr)));
+                i += 1;
+                continue;
+            }
+
+            let is_printable_out = instrs[i].opcode == Some(19) && instrs[i].args.len() == 1 &&
+                                    Self::printable(instrs[i].args[0]);
+            if is_printable_out {
+                let mut j = i;
+                let mut text = String::new();
+                while j < instrs.len() && instrs[j].opcode == Some(19) &&
+                      instrs[j].args.len() == 1 && Self::printable(instrs[j].args[0]) {
+                    text.push((instrs[j].args[0] as u8) as char);
+                    j += 1;
+                }
+                for (k, instr) in instrs[i..j].iter().enumerate() {
+                    let line = Self::format_instruction(instr);
+                    if k == 0 {
+                        out.push((instr.addr, format!("{}  ; {:?}", line, text)));
+                    } else {
+                        out.push((instr.addr, line));
+                    }
+                }
+                i = j;
+                continue;
+            }
+
+            out.push((instrs[i].addr, Self::format_instruction(&instrs[i])));
+            i += 1;
+        }
+        out
+    }
+
+    fn printable(val: u16) -> bool {
+        if val > 255 {
+            return false;
+        }
+        let c = val as u8 as char;
+        c == '\n' || (' '..='~').contains(&c)
+    }
+
+    fn format_instruction(instr: &Instr) -> String {
+        let opcode = instr.opcode.expect("data word has no mnemonic");
+        let mnemonic = Self::mnemonic(opcode);
+        if instr.args.is_empty() {
+            format!("{}: {}", instr.addr, mnemonic)
+        } else {
+            let args: Vec<String> = instr.args.iter().cloned().map(Self::format_arg).collect();
+            format!("{}: {} {}", instr.addr, mnemonic, args.join(" "))
+        }
+    }
+
+    fn format_arg(arg: u16) -> String {
+        if (32768..=32775).contains(&arg) {
+            format!("r{}", arg - 32768)
+        } else {
+            format!("{}", arg)
+        }
+    }
+
+    fn mnemonic(opcode: u16) -> &'static str {
+        match opcode {
+            0 => "HALT",
+            1 => "SET",
+            2 => "PUSH",
+            3 => "POP",
+            4 => "EQ",
+            5 => "GT",
+            6 => "JMP",
+            7 => "JT",
+            8 => "JF",
+            9 => "ADD",
+            10 => "MULT",
+            11 => "MOD",
+            12 => "AND",
+            13 => "OR",
+            14 => "NOT",
+            15 => "RMEM",
+            16 => "WMEM",
+            17 => "CALL",
+            18 => "RET",
+            19 => "OUT",
+            20 => "IN",
+            21 => "NOOP",
+            _ => "DATA",
+        }
+    }
+
+    /// The number of words (including the opcode itself) an instruction
+    /// occupies in memory.
+    fn op_length(opcode: u16) -> usize {
+        match opcode {
+            0 => 1,
+            1 => 3,
+            2 => 2,
+            3 => 2,
+            4 => 4,
+            5 => 4,
+            6 => 2,
+            7 => 3,
+            8 => 3,
+            9 => 4,
+            10 => 4,
+            11 => 4,
+            12 => 4,
+            13 => 4,
+            14 => 3,
+            15 => 3,
+            16 => 3,
+            17 => 2,
+            18 => 1,
+            19 => 2,
+            20 => 2,
+            21 => 1,
+            _ => 1,
+        }
+    }
+
+    /// Assembles a textual program into a little-endian `u16` image
+    /// suitable for `load_from_words`.
     ///
-    /// # Panics
+    /// Each source line is blank, a `; comment`, a `label:` definition, a
+    /// `data <values...>` directive emitting raw words, or a mnemonic
+    /// followed by its arguments, e.g. `set r0 5` or `jmp label`.
+    /// Register arguments are written as `r0`..`r7`; any other argument is
+    /// parsed as a numeric literal or resolved against a label, which may
+    /// be defined earlier or later in the source.
+    pub fn assemble(src: &str) -> Result<Vec<u16>, AsmError> {
+        let mut labels = HashMap::new();
+        let mut stmts = vec![];
+        let mut addr: u16 = 0;
+
+        for (i, raw_line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            let line = Self::strip_comment(raw_line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(label) = line.strip_suffix(':') {
+                let label = label.trim().to_string();
+                if labels.contains_key(&label) {
+                    return Err(AsmError::DuplicateLabel {
+                        label: label,
+                        line: line_no,
+                    });
+                }
+                labels.insert(label, addr);
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let head = tokens.next().unwrap();
+            if head == "data" {
+                let values: Vec<String> = tokens.map(|t| t.to_string()).collect();
+                addr += values.len() as u16;
+                stmts.push((line_no, Stmt::Data(values)));
+            } else {
+                let opcode = try!(Self::mnemonic_opcode(head).ok_or_else(|| {
+                    AsmError::UnknownMnemonic {
+                        mnemonic: head.to_string(),
+                        line: line_no,
+                    }
+                }));
+                let args: Vec<String> = tokens.map(|t| t.to_string()).collect();
+                let expected = Self::op_length(opcode) - 1;
+                if args.len() != expected {
+                    return Err(AsmError::WrongArgCount {
+                        mnemonic: head.to_string(),
+                        expected: expected,
+                        found: args.len(),
+                        line: line_no,
+                    });
+                }
+                addr += Self::op_length(opcode) as u16;
+                stmts.push((line_no, Stmt::Instr(opcode, args)));
+            }
+        }
+
+        let mut words = vec![];
+        for (line_no, stmt) in stmts {
+            match stmt {
+                Stmt::Data(values) => {
+                    for v in values {
+                        words.push(try!(Self::resolve_arg(&v, &labels, line_no)));
+                    }
+                }
+                Stmt::Instr(opcode, args) => {
+                    words.push(opcode);
+                    for a in args {
+                        words.push(try!(Self::resolve_arg(&a, &labels, line_no)));
+                    }
+                }
+            }
+        }
+
+        Ok(words)
+    }
+
+    /// Loads an already-assembled program into memory, overwriting it from
+    /// address 0. Returns the number of words written.
+    pub fn load_from_words(&mut self, words: &[u16]) -> usize {
+        let len = words.len().min(self.memory.len());
+        self.memory[..len].copy_from_slice(&words[..len]);
+        len
+    }
+
+    fn strip_comment(line: &str) -> &str {
+        match line.find(';') {
+            Some(idx) => &line[..idx],
+            None => line,
+        }
+    }
+
+    fn resolve_arg(text: &str, labels: &HashMap<String, u16>, line_no: usize) -> Result<u16, AsmError> {
+        if let Some(rest) = text.strip_prefix('r') {
+            if let Ok(reg_num) = rest.parse::<u16>() {
+                if reg_num <= 7 {
+                    return Ok(32768 + reg_num);
+                } else {
+                    return Err(AsmError::InvalidArgument {
+                        text: text.to_string(),
+                        line: line_no,
+                    });
+                }
+            }
+        }
+        if let Ok(n) = text.parse::<u16>() {
+            return Ok(n);
+        }
+        match labels.get(text) {
+            Some(&addr) => Ok(addr),
+            None => {
+                Err(AsmError::UndefinedLabel {
+                    label: text.to_string(),
+                    line: line_no,
+                })
+            }
+        }
+    }
+
+    fn mnemonic_opcode(name: &str) -> Option<u16> {
+        match name.to_uppercase().as_str() {
+            "HALT" => Some(0),
+            "SET" => Some(1),
+            "PUSH" => Some(2),
+            "POP" => Some(3),
+            "EQ" => Some(4),
+            "GT" => Some(5),
+            "JMP" => Some(6),
+            "JT" => Some(7),
+            "JF" => Some(8),
+            "ADD" => Some(9),
+            "MULT" => Some(10),
+            "MOD" => Some(11),
+            "AND" => Some(12),
+            "OR" => Some(13),
+            "NOT" => Some(14),
+            "RMEM" => Some(15),
+            "WMEM" => Some(16),
+            "CALL" => Some(17),
+            "RET" => Some(18),
+            "OUT" => Some(19),
+            "IN" => Some(20),
+            "NOOP" => Some(21),
+            _ => None,
+        }
+    }
+
+    /// Decodes and runs the next instruction.
     ///
-    /// Panics on stack underflow, an invalid instruction argument,
-    /// or an EOF on stdin.
-    pub fn tick(&mut self) -> bool {
-        match self.decode() {
+    /// Returns `Ok(false)` if a `HALT`, or a `RET` with an empty stack, was
+    /// encountered, `Ok(true)` otherwise. Returns `Err` if the instruction stream could not be
+    /// decoded or executed, for example on stack underflow, an invalid
+    /// instruction argument, an unrecognized opcode, or an EOF on stdin.
+    pub fn tick(&mut self) -> Result<bool, VmError> {
+        let ip = self.ip;
+        match try!(self.decode()) {
             Op::Halt => {
                 println!("Got HALT, stopping.");
-                return false;
+                return Ok(false);
             }
             Op::Set(a, b) => {
-                let val = self.value(b);
+                let val = try!(self.value(b));
                 self.set_register(a, val);
             }
             Op::Push(a) => {
-                let val = self.value(a);
+                let val = try!(self.value(a));
                 self.stack.push(val);
             }
             Op::Pop(a) => {
-                let top = self.stack.pop().expect("Stack underflow");
+                let top = try!(self.stack.pop().ok_or(VmError::StackUnderflow));
                 self.set_register(a, top);
             }
             Op::Eq(a, b, c) => {
-                if self.value(b) == self.value(c) {
+                if try!(self.value(b)) == try!(self.value(c)) {
                     self.set_register(a, 1);
                 } else {
                     self.set_register(a, 0);
                 }
             }
             Op::Gt(a, b, c) => {
-                if self.value(b) > self.value(c) {
+                if try!(self.value(b)) > try!(self.value(c)) {
                     self.set_register(a, 1);
                 } else {
                     self.set_register(a, 0);
                 }
             }
             Op::Jmp(a) => {
-                self.ip = self.value(a);
+                self.ip = try!(self.value(a));
             }
             Op::Jt(a, b) => {
-                if self.value(a) != 0 {
-                    self.ip = self.value(b);
+                if try!(self.value(a)) != 0 {
+                    self.ip = try!(self.value(b));
                 }
             }
             Op::Jf(a, b) => {
-                if self.value(a) == 0 {
-                    self.ip = self.value(b);
+                if try!(self.value(a)) == 0 {
+                    self.ip = try!(self.value(b));
                 }
             }
             Op::Add(a, b, c) => {
-                let sum = self.add(self.value(b), self.value(c));
+                let sum = self.add(try!(self.value(b)), try!(self.value(c)));
                 self.set_register(a, sum);
             }
             Op::Mult(a, b, c) => {
-                let res = self.mult(self.value(b), self.value(c));
+                let res = self.mult(try!(self.value(b)), try!(self.value(c)));
                 self.set_register(a, res);
             }
             Op::Mod(a, b, c) => {
-                let res = self.value(b) % self.value(c);
+                let res = try!(self.value(b)) % try!(self.value(c));
                 self.set_register(a, res);
             }
             Op::And(a, b, c) => {
-                let and = self.value(b) & self.value(c);
+                let and = try!(self.value(b)) & try!(self.value(c));
                 self.set_register(a, and);
             }
             Op::Or(a, b, c) => {
-                let or = self.value(b) | self.value(c);
+                let or = try!(self.value(b)) | try!(self.value(c));
                 self.set_register(a, or);
             }
             Op::Not(a, b) => {
-                let not = (!self.value(b)) & 32767;
+                let not = (!try!(self.value(b))) & 32767;
                 self.set_register(a, not);
             }
             Op::Rmem(a, b) => {
-                let val = self.memory[self.value(b) as usize];
+                let addr = try!(self.value(b));
+                let val = try!(self.memory
+                                   .get(addr as usize)
+                                   .cloned()
+                                   .ok_or(VmError::MemoryOutOfBounds(addr)));
                 self.set_register(a, val);
             }
             Op::Wmem(a, b) => {
-                let val = self.value(b);
-                let dest = self.value(a);
+                let val = try!(self.value(b));
+                let dest = try!(self.value(a));
+                if dest as usize >= self.memory.len() {
+                    return Err(VmError::MemoryOutOfBounds(dest));
+                }
                 self.memory[dest as usize] = val;
             }
             Op::Call(a) => {
-                self.stack.push(self.ip);
-                self.ip = self.value(a);
+                let target = try!(self.value(a));
+                if self.call_handlers.contains_key(&target) {
+                    let mut handler = self.call_handlers.remove(&target).unwrap();
+                    let result = handler(self);
+                    self.call_handlers.insert(target, handler);
+                    try!(result);
+                } else {
+                    self.stack.push(self.ip);
+                    self.call_trace.push(self.ip);
+                    self.ip = target;
+                }
             }
             Op::Ret => {
                 match self.stack.pop() {
                     None => {
                         println!("Empty stack on RET. Halting.");
-                        return false;
+                        return Ok(false);
                     }
                     Some(addr) => {
+                        self.call_trace.pop();
                         self.ip = addr;
                     }
                 }
             }
             Op::Out(a) => {
-                print!("{}", (self.value(a) as u8) as char);
+                let val = try!(self.value(a));
+                if self.output_handler.is_some() {
+                    let mut handler = self.output_handler.take().unwrap();
+                    let result = handler(self, val);
+                    self.output_handler = Some(handler);
+                    try!(result);
+                } else {
+                    print!("{}", (val as u8) as char);
+                }
             }
             Op::In(a) => {
-                let c: u8 = std::io::stdin()
-                                .bytes()
-                                .nth(0)
-                                .expect("EOF")
-                                .expect("EOF");
-                self.set_register(a, c as u16);
+                let val = if self.input_handler.is_some() {
+                    let mut handler = self.input_handler.take().unwrap();
+                    let result = handler(self);
+                    self.input_handler = Some(handler);
+                    try!(result)
+                } else {
+                    let byte = try!(std::io::stdin().bytes().nth(0).ok_or(VmError::InputEof));
+                    let c: u8 = try!(byte.or(Err(VmError::InputEof)));
+                    c as u16
+                };
+                self.set_register(a, val);
             }
             Op::Noop => {}
-            invalid => {
-                println!("Not handling: {:?}", invalid);
-                return false;
+            Op::Invalid(op) => {
+                return Err(VmError::InvalidOpcode { op: op, ip: ip });
             }
         }
 
-        true
+        Ok(true)
     }
 
-    /// Runs the machine until HALT or an invalid instruction.
-    pub fn run(&mut self) {
-        while self.tick() {
+    /// Runs the machine until `HALT`, a `RET` with an empty stack, or an
+    /// error is encountered.
+    pub fn run(&mut self) -> Result<(), VmError> {
+        while try!(self.tick()) {
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibrate_teleporter_finds_a_matching_r8() {
+        let expected = Machine::teleporter_check(4, 1, 5);
+        let found = Machine::calibrate_teleporter(expected)
+            .expect("a matching r8 should exist for a value teleporter_check itself produced");
+        assert_eq!(Machine::teleporter_check(4, 1, found), expected);
+    }
+
+    #[test]
+    fn calibrate_teleporter_returns_none_when_no_r8_matches() {
+        // f(4, 1, r8) is never 0 for any r8 (its smallest value is 2).
+        assert_eq!(Machine::calibrate_teleporter(0), None);
+    }
+
+    #[test]
+    fn cont_reports_watchpoint_tripped_by_a_skipped_breakpoint() {
+        // SET register0, 42; HALT
+        let mut machine = Machine::new();
+        machine.load_from_words(&[1, 32768, 42, 0]);
+        machine.add_breakpoint(0);
+        machine.add_watchpoint(WatchTarget::Register(0)).unwrap();
+
+        assert_eq!(machine.step().unwrap(), StepOutcome::Breakpoint(0));
+        assert_eq!(machine.cont().unwrap(),
+                   StepOutcome::Watchpoint(WatchTarget::Register(0), 0, 42));
+    }
+
+    #[test]
+    fn add_watchpoint_rejects_an_out_of_range_register() {
+        let mut machine = Machine::new();
+        assert_eq!(machine.add_watchpoint(WatchTarget::Register(8)),
+                   Err(VmError::InvalidArgument(8)));
+    }
+
+    #[test]
+    fn add_watchpoint_rejects_an_out_of_range_memory_address() {
+        let mut machine = Machine::new();
+        assert_eq!(machine.add_watchpoint(WatchTarget::Memory(40000)),
+                   Err(VmError::MemoryOutOfBounds(40000)));
+    }
+
+    #[test]
+    fn disassemble_renders_a_data_range_as_a_single_data_entry() {
+        let mut machine = Machine::new();
+        machine.load_from_words(&[0, 12345, 54321, 0]);
+        let listing = machine.disassemble(0, 4, &[(1, 3)]);
+        assert_eq!(listing,
+                   vec![(0, "0: HALT".to_string()),
+                        (1, "1: <data>".to_string()),
+                        (3, "3: HALT".to_string())]);
+    }
+
+    #[test]
+    fn disassemble_annotates_a_run_of_printable_outs_with_the_string() {
+        let mut machine = Machine::new();
+        machine.load_from_words(&[19, 72, 19, 73, 0]); // OUT 'H'; OUT 'I'; HALT
+        let listing = machine.disassemble(0, 5, &[]);
+        assert_eq!(listing,
+                   vec![(0, "0: OUT 72  ; \"HI\"".to_string()),
+                        (2, "2: OUT 73".to_string()),
+                        (4, "4: HALT".to_string())]);
+    }
+
+    #[test]
+    fn assemble_resolves_forward_and_backward_label_references() {
+        let src = "jmp forward\nback:\nnoop\nforward:\njmp back\n";
+        let words = Machine::assemble(src).unwrap();
+        assert_eq!(words, vec![6, 3, 21, 6, 2]);
+    }
+
+    #[test]
+    fn assemble_data_directive_emits_raw_words() {
+        let words = Machine::assemble("data 1 2 3\n").unwrap();
+        assert_eq!(words, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn assemble_reports_unknown_mnemonic() {
+        assert_eq!(Machine::assemble("frobnicate r0\n"),
+                   Err(AsmError::UnknownMnemonic {
+                       mnemonic: "frobnicate".to_string(),
+                       line: 1,
+                   }));
+    }
+
+    #[test]
+    fn assemble_reports_wrong_arg_count() {
+        assert_eq!(Machine::assemble("jmp\n"),
+                   Err(AsmError::WrongArgCount {
+                       mnemonic: "jmp".to_string(),
+                       expected: 1,
+                       found: 0,
+                       line: 1,
+                   }));
+    }
+
+    #[test]
+    fn assemble_reports_invalid_argument() {
+        assert_eq!(Machine::assemble("jmp r9\n"),
+                   Err(AsmError::InvalidArgument {
+                       text: "r9".to_string(),
+                       line: 1,
+                   }));
+    }
+
+    #[test]
+    fn assemble_reports_undefined_label() {
+        assert_eq!(Machine::assemble("jmp nowhere\n"),
+                   Err(AsmError::UndefinedLabel {
+                       label: "nowhere".to_string(),
+                       line: 1,
+                   }));
+    }
+
+    #[test]
+    fn assemble_reports_duplicate_label() {
+        assert_eq!(Machine::assemble("a:\nnoop\na:\nnoop\n"),
+                   Err(AsmError::DuplicateLabel {
+                       label: "a".to_string(),
+                       line: 3,
+                   }));
+    }
+
+    #[test]
+    fn call_handler_runs_instead_of_the_bytecode_at_its_address() {
+        let mut machine = Machine::new();
+        let mut words = vec![0u16; 103];
+        words[0] = 17; // CALL 100
+        words[1] = 100;
+        words[2] = 0; // HALT
+        // If the handler didn't fire, this would run instead: SET r0, 13.
+        words[100] = 1;
+        words[101] = 32768;
+        words[102] = 13;
+        machine.load_from_words(&words);
+
+        machine.register_call_handler(100, |m| {
+            m.set_register(32768, 99);
+            Ok(())
+        });
+
+        machine.run().unwrap();
+        assert_eq!(machine.registers()[0], 99);
+    }
+
+    #[test]
+    fn input_handler_overrides_stdin() {
+        let mut machine = Machine::new();
+        machine.load_from_words(&[20, 32768, 0]); // IN r0; HALT
+        machine.set_input_handler(|_| Ok(7));
+
+        machine.run().unwrap();
+        assert_eq!(machine.registers()[0], 7);
+    }
+
+    #[test]
+    fn output_handler_overrides_stdout() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+
+        let mut machine = Machine::new();
+        // SET r0, 65; OUT r0; HALT
+        machine.load_from_words(&[1, 32768, 65, 19, 32768, 0]);
+
+        let captured = Rc::new(RefCell::new(vec![]));
+        let captured_handler = captured.clone();
+        machine.set_output_handler(move |_, val| {
+            captured_handler.borrow_mut().push(val);
+            Ok(())
+        });
+
+        machine.run().unwrap();
+        assert_eq!(*captured.borrow(), vec![65]);
+    }
+
+    #[test]
+    fn restore_reverts_registers_memory_stack_and_ip_to_the_snapshot() {
+        let mut machine = Machine::new();
+        machine.load_from_words(&[1, 32768, 7, 0]); // SET r0, 7; HALT
+        machine.step().unwrap();
+        let snapshot = machine.snapshot();
+
+        machine.registers[0] = 999;
+        machine.memory[0] = 111;
+        machine.stack.push(42);
+        machine.ip = 999;
+
+        machine.restore(&snapshot);
+
+        assert_eq!(machine.registers()[0], 7);
+        assert_eq!(machine.memory()[0], 1);
+        assert!(machine.stack().is_empty());
+        assert_eq!(machine.ip(), 3);
+    }
+
+    #[test]
+    fn scripted_input_feeds_in_then_reports_eof_once_exhausted() {
+        let mut machine = Machine::new();
+        machine.load_from_words(&[20, 32768, 20, 32768, 0]); // IN r0; IN r0; HALT
+        machine.set_scripted_input(VecDeque::from(vec![b'A']));
+
+        assert_eq!(machine.step().unwrap(), StepOutcome::Continued);
+        assert_eq!(machine.registers()[0], b'A' as u16);
+
+        assert_eq!(machine.step().unwrap_err(), VmError::InputEof);
     }
 }
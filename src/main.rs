@@ -1,17 +1,167 @@
 extern crate synacor;
 
 use std::env;
+use std::io;
+use std::io::Write;
+use std::process;
 use self::synacor::*;
 
 fn main() {
-    match env::args().nth(1) {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
         None => println!("Please provide an input path."),
-        Some(path) => {
-            let mut machine = Machine::new();
-            let read = machine.load(&path).unwrap_or(0);
-            println!("Read {} bytes, executing.", read);
-            println!("=========================");
-            machine.run();
+        Some("disasm") => run_disasm(&args[2..]),
+        Some("debug") => run_debug(&args[2..]),
+        Some(path) => run_program(path),
+    }
+}
+
+fn run_program(path: &str) {
+    let mut machine = Machine::new();
+    let read = machine.load(path).unwrap_or(0);
+    println!("Read {} bytes, executing.", read);
+    println!("=========================");
+    if let Err(e) = machine.run() {
+        println!("Execution stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run_disasm(args: &[String]) {
+    let path = match args.get(0) {
+        Some(path) => path,
+        None => {
+            println!("Please provide an input path to disassemble.");
+            return;
+        }
+    };
+
+    let mut machine = Machine::new();
+    if let Err(e) = machine.load(path) {
+        println!("Failed to load {}: {}", path, e);
+        process::exit(1);
+    }
+
+    let start = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+    let end = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(32768);
+    for (_, line) in machine.disassemble(start, end, &[]) {
+        println!("{}", line);
+    }
+}
+
+/// A minimal stepping-debugger REPL, handy for the challenge's
+/// self-modifying code sections. Supports:
+///
+///   b <addr>   set a breakpoint at `addr`
+///   s          single-step one instruction
+///   c          continue until a breakpoint, watchpoint, or halt
+///   regs       dump registers, stack, ip, and the call-stack backtrace
+///   mem <addr> dump the memory cell at `addr`
+///   watch reg <n>    watch register `n` (0-7) for changes
+///   watch mem <addr> watch memory address `addr` for changes
+///   q          quit
+fn run_debug(args: &[String]) {
+    let path = match args.get(0) {
+        Some(path) => path,
+        None => {
+            println!("Please provide an input path to debug.");
+            return;
+        }
+    };
+
+    let mut machine = Machine::new();
+    if let Err(e) = machine.load(path) {
+        println!("Failed to load {}: {}", path, e);
+        process::exit(1);
+    }
+
+    let stdin = io::stdin();
+    loop {
+        print!("(dbg) ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+
+        let mut parts = line.trim().split_whitespace();
+        match parts.next() {
+            Some("b") => {
+                match parts.next().and_then(|a| a.parse().ok()) {
+                    Some(addr) => {
+                        machine.add_breakpoint(addr);
+                        println!("Breakpoint set at {}", addr);
+                    }
+                    None => println!("Usage: b <addr>"),
+                }
+            }
+            Some("s") => print_step(machine.step()),
+            Some("c") => print_step(machine.cont()),
+            Some("regs") => {
+                println!("ip={}", machine.ip());
+                println!("registers={:?}", machine.registers());
+                println!("stack={:?}", machine.stack());
+                println!("backtrace={:?}", machine.backtrace());
+            }
+            Some("mem") => {
+                match parts.next().and_then(|a| a.parse::<u16>().ok()) {
+                    Some(addr) => println!("{}", format_mem(&machine, addr)),
+                    None => println!("Usage: mem <addr>"),
+                }
+            }
+            Some("watch") => {
+                let target = match (parts.next(), parts.next().and_then(|a| a.parse::<u16>().ok())) {
+                    (Some("reg"), Some(n)) => Some(WatchTarget::Register(n)),
+                    (Some("mem"), Some(addr)) => Some(WatchTarget::Memory(addr)),
+                    _ => {
+                        println!("Usage: watch reg <n> | watch mem <addr>");
+                        None
+                    }
+                };
+                if let Some(target) = target {
+                    match machine.add_watchpoint(target) {
+                        Ok(()) => println!("Watching {:?}", target),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+            }
+            Some("q") => break,
+            Some(other) => println!("Unknown command: {}", other),
+            None => {}
         }
     }
 }
+
+fn print_step(result: Result<StepOutcome, VmError>) {
+    match result {
+        Ok(outcome) => println!("{:?}", outcome),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Formats the `mem` command's reply, bounds-checking `addr` against
+/// `machine`'s memory instead of indexing it directly.
+fn format_mem(machine: &Machine, addr: u16) -> String {
+    match machine.memory().get(addr as usize) {
+        Some(value) => format!("{}: {}", addr, value),
+        None => format!("Address out of range: {}", addr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_mem_reports_in_range_values() {
+        let machine = Machine::new();
+        assert_eq!(format_mem(&machine, 10), "10: 0");
+    }
+
+    #[test]
+    fn format_mem_rejects_out_of_range_addresses() {
+        let machine = Machine::new();
+        assert_eq!(format_mem(&machine, 40000), "Address out of range: 40000");
+    }
+}